@@ -0,0 +1,101 @@
+use ethers_core::types::{Address, Bytes, H256, U256};
+use sputnik::backend::{Apply, ApplyBackend, Backend, Basic};
+use std::collections::BTreeMap;
+
+// Writes a single-account incremental mutation directly into the backend via
+// `ApplyBackend`, the same low-level mechanism Sputnik itself uses to commit
+// state after a real call. `code: None` / empty `storage` leave the existing
+// code/slots untouched; only the fields actually passed in are changed.
+fn modify<S: Backend + ApplyBackend>(
+    state: &mut S,
+    address: Address,
+    basic: Basic,
+    code: Option<Vec<u8>>,
+    storage: Vec<(H256, H256)>,
+) {
+    state.apply(
+        vec![Apply::Modify {
+            address,
+            basic,
+            code,
+            storage,
+            reset_storage: false,
+        }],
+        Vec::<sputnik::backend::Log>::new(),
+        false,
+    );
+}
+
+pub fn set_balance<S: Backend + ApplyBackend>(state: &mut S, address: Address, balance: U256) {
+    let nonce = state.basic(address).nonce;
+    modify(state, address, Basic { balance, nonce }, None, Vec::new());
+}
+
+pub fn set_nonce<S: Backend + ApplyBackend>(state: &mut S, address: Address, nonce: U256) {
+    let balance = state.basic(address).balance;
+    modify(state, address, Basic { balance, nonce }, None, Vec::new());
+}
+
+pub fn set_code<S: Backend + ApplyBackend>(state: &mut S, address: Address, code: Bytes) {
+    let basic = state.basic(address);
+    modify(state, address, basic, Some(code.to_vec()), Vec::new());
+}
+
+pub fn set_storage_at<S: Backend + ApplyBackend>(
+    state: &mut S,
+    address: Address,
+    slot: H256,
+    value: H256,
+) {
+    let basic = state.basic(address);
+    modify(state, address, basic, None, vec![(slot, value)]);
+}
+
+/// Holds `evm_snapshot`/`evm_revert` checkpoints: full clones of the executor
+/// state, keyed by an incrementing id. Reverting to an id also drops every
+/// snapshot taken after it, mirroring anvil/hardhat semantics.
+#[derive(Default)]
+pub struct Snapshots<S> {
+    next_id: U256,
+    states: BTreeMap<U256, S>,
+}
+
+impl<S> Snapshots<S> {
+    pub fn new() -> Self {
+        Self {
+            next_id: U256::zero(),
+            states: BTreeMap::new(),
+        }
+    }
+}
+
+impl<S: Clone> Snapshots<S> {
+    pub fn take(&mut self, state: &S) -> U256 {
+        let id = self.next_id;
+        self.next_id += U256::one();
+        self.states.insert(id, state.clone());
+        id
+    }
+
+    pub fn revert(&mut self, id: U256) -> Option<S> {
+        let state = self.states.remove(&id)?;
+        self.states.retain(|&k, _| k < id);
+        Some(state)
+    }
+}
+
+/// Pending block-production controls (`anvil_setNextBlockBaseFeePerGas`)
+/// applied the next time `Forge::mine` runs.
+//
+// This crate doesn't drive Sputnik's block environment (vicinity) yet -
+// `block_number`/`block_timestamp` there are fixed at executor construction -
+// so `mine()` only advances the bookkeeping this crate itself derives state
+// from (the fee model), the same spirit as the existing TODOs around block
+// reconstruction in `middleware.rs`. There's deliberately no
+// `next_timestamp` field here: unlike the base fee, nothing downstream can
+// actually honor a queued timestamp, so `Forge::set_next_block_timestamp`
+// reports that up front as an error instead of silently accepting it.
+#[derive(Default)]
+pub struct PendingBlock {
+    pub next_base_fee: Option<U256>,
+}
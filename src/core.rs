@@ -1,7 +1,7 @@
 use async_trait::async_trait;
 use ethers_core::types::{
-    transaction::eip2718::TypedTransaction, Address, BlockId, BlockNumber, Bytes, NameOrAddress,
-    H256, U256, U64,
+    transaction::eip2718::TypedTransaction, Address, BlockId, BlockNumber, Bytes, Log,
+    NameOrAddress, H256, U256, U64,
 };
 use ethers_providers::{JsonRpcClient, Middleware, Provider, ProviderError};
 use evm_adapters::Evm;
@@ -13,7 +13,12 @@ use std::{
 };
 use tokio::sync::RwLock;
 
+use crate::cheats::{self, PendingBlock, Snapshots};
+use crate::erc4337::{decode_simulate_validation_revert, UserOperation, ValidationReport, ValidationTracer};
 use crate::evm::VmShow;
+use crate::fees::FeeModel;
+use crate::logs::LogIndex;
+use crate::trace::StepTracer;
 
 const DEFAULT_SENDER: &str = "0xD3D13a578a53685B4ac36A1Bab31912D2B2A2F36";
 
@@ -52,6 +57,10 @@ impl<T> Inner<T> {
 pub struct Forge<M, E, S> {
     pub vm: Arc<RwLock<E>>,
     pub inner: Inner<M>,
+    pub logs: Arc<LogIndex>,
+    pub fees: Arc<FeeModel>,
+    pub snapshots: Arc<RwLock<Snapshots<S>>>,
+    pub pending_block: Arc<RwLock<PendingBlock>>,
     _ghost: PhantomData<S>,
 }
 
@@ -60,6 +69,10 @@ impl<E, S> Forge<Provider<NoClient>, E, S> {
         Self {
             vm,
             inner: Inner::not(),
+            logs: Arc::new(LogIndex::new()),
+            fees: Arc::new(FeeModel::new()),
+            snapshots: Arc::new(RwLock::new(Snapshots::new())),
+            pending_block: Arc::new(RwLock::new(PendingBlock::default())),
             _ghost: PhantomData,
         }
     }
@@ -69,6 +82,10 @@ impl<M, E, S> Forge<M, E, S> {
         Self {
             vm,
             inner: Inner::Use(inner),
+            logs: Arc::new(LogIndex::new()),
+            fees: Arc::new(FeeModel::new()),
+            snapshots: Arc::new(RwLock::new(Snapshots::new())),
+            pending_block: Arc::new(RwLock::new(PendingBlock::default())),
             _ghost: PhantomData,
         }
     }
@@ -80,6 +97,41 @@ impl<M, E, S> Forge<M, E, S> {
     }
 }
 
+impl<M, E, S0> Forge<M, E, crate::fork::ForkedBackend<S0, M>>
+where
+    M: Clone + 'static,
+    S0: 'static,
+{
+    /// Builds a `Forge` whose executor backend lazily forks off `inner` at
+    /// `fork_block`: any account/slot/block-hash `local` doesn't have is
+    /// fetched from `inner` and cached (see [`crate::fork::ForkedBackend`]).
+    ///
+    /// Sputnik's `Executor` borrows its backend rather than owning it, so the
+    /// forked backend is leaked for the process lifetime to get a `'static`
+    /// reference to hand `build_executor` - an acceptable tradeoff for a
+    /// long-lived local fork node, the same one `anvil`-style forking tools make.
+    pub fn new_forked(
+        local: S0,
+        inner: M,
+        fork_block: U256,
+        build_executor: impl FnOnce(&'static crate::fork::ForkedBackend<S0, M>) -> E,
+    ) -> Self {
+        let backend: &'static crate::fork::ForkedBackend<S0, M> = Box::leak(Box::new(
+            crate::fork::ForkedBackend::new(local, Inner::Use(inner.clone()), fork_block),
+        ));
+        let vm = Arc::new(RwLock::new(build_executor(backend)));
+        Self {
+            vm,
+            inner: Inner::Use(inner),
+            logs: Arc::new(LogIndex::new()),
+            fees: Arc::new(FeeModel::new()),
+            snapshots: Arc::new(RwLock::new(Snapshots::new())),
+            pending_block: Arc::new(RwLock::new(PendingBlock::default())),
+            _ghost: PhantomData,
+        }
+    }
+}
+
 pub enum TxOutput {
     CallRes(Bytes),
     CreateRes(Address),
@@ -104,7 +156,7 @@ pub struct TxRes<Ex> {
     pub output: TxOutput,
     pub exit: Ex,
     pub gas: u64,
-    pub logs: Vec<String>,
+    pub logs: Vec<Log>,
 }
 impl<M, E, S> Forge<M, E, S>
 where
@@ -134,8 +186,13 @@ where
         if let Some(fut) = maybe_to {
             // (contract) call
             let to = fut.await?;
-            let (bytes, exit, gas, logs) =
-                self.vm_mut().await.call_raw(*from, to, data, *val, false)?;
+            let mut vm = self.vm_mut().await;
+            // `call_raw` also hands back a best-effort `Vec<String>` of its
+            // own, but that's lossy once formatted as text - read the real
+            // `LOGn`s directly off the trace instead.
+            let (call_result, logs) =
+                crate::logs::capture_logs(|| vm.call_raw(*from, to, data, *val, false));
+            let (bytes, exit, gas, _logs) = call_result?;
             Ok(TxRes {
                 output: TxOutput::CallRes(bytes),
                 exit,
@@ -144,7 +201,10 @@ where
             })
         } else {
             // contract deployment
-            let (addr, exit, gas, logs) = self.vm_mut().await.deploy(*from, data.clone(), *val)?;
+            let mut vm = self.vm_mut().await;
+            let (deploy_result, logs) =
+                crate::logs::capture_logs(|| vm.deploy(*from, data.clone(), *val));
+            let (addr, exit, gas, _logs) = deploy_result?;
             Ok(TxRes {
                 output: TxOutput::CreateRes(addr),
                 exit,
@@ -154,6 +214,290 @@ where
         }
     }
 
+    // Same as `apply_tx`, but installs a `StepTracer` as the sputnik step
+    // listener for the duration of the call/deploy and hands it back
+    // alongside the usual `TxRes` so callers (`trace_call`,
+    // `debug_trace_call`) can turn it into a struct-log or call-frame trace.
+    pub async fn apply_tx_traced(
+        &self,
+        tx: &TypedTransaction,
+    ) -> Result<(TxRes<E::ReturnReason>, StepTracer), <Self as Middleware>::Error> {
+        let default_from = DEFAULT_SENDER.parse().unwrap();
+        let default_val = U256::zero();
+
+        let from = tx.from().unwrap_or(&default_from);
+        let maybe_to = tx.to().map(|id| async move {
+            match id {
+                NameOrAddress::Name(ens) => self.resolve_name(ens).await,
+                NameOrAddress::Address(addr) => Ok(*addr),
+            }
+        });
+        let data = tx.data().map_or(Default::default(), |d| d.clone());
+        let val = tx.value().unwrap_or(&default_val);
+
+        let gas_limit = self.vm().await.gas_limit().as_u64();
+
+        if let Some(fut) = maybe_to {
+            let to = fut.await?;
+            let mut vm = self.vm_mut().await;
+            let (call_result, tracer) = crate::trace::trace_call(gas_limit, || {
+                vm.call_raw(*from, to, data, *val, false)
+            });
+            let (bytes, exit, gas, _logs) = call_result?;
+            let logs = tracer.logs().to_vec();
+            Ok((
+                TxRes {
+                    output: TxOutput::CallRes(bytes),
+                    exit,
+                    gas,
+                    logs,
+                },
+                tracer,
+            ))
+        } else {
+            let mut vm = self.vm_mut().await;
+            let (deploy_result, tracer) =
+                crate::trace::trace_call(gas_limit, || vm.deploy(*from, data.clone(), *val));
+            let (addr, exit, gas, _logs) = deploy_result?;
+            let logs = tracer.logs().to_vec();
+            Ok((
+                TxRes {
+                    output: TxOutput::CreateRes(addr),
+                    exit,
+                    gas,
+                    logs,
+                },
+                tracer,
+            ))
+        }
+    }
+
+    // `debug_traceTransaction`-style struct-log trace of a call, without
+    // committing its state changes (same clone/reset trick as `call()`).
+    pub async fn debug_trace_call(
+        &self,
+        tx: &TypedTransaction,
+    ) -> Result<crate::trace::StructLogTrace, <Self as Middleware>::Error> {
+        let state = (*self.vm().await.state()).clone();
+
+        let (res, tracer) = self.apply_tx_traced(tx).await?;
+        let failed = !E::is_success(&res.exit);
+        let return_value = res.output.maybe_bytes().unwrap_or_default();
+
+        self.vm_mut().await.reset(state);
+
+        Ok(tracer.into_struct_log_trace(failed, return_value))
+    }
+
+    /// `evm_snapshot`: stashes a clone of the current executor state and
+    /// returns an id that can later be passed to [`Forge::revert_to`].
+    pub async fn snapshot(&self) -> U256
+    where
+        S: Clone,
+    {
+        let state = (*self.vm().await.state()).clone();
+        self.snapshots.write().await.take(&state)
+    }
+
+    /// `evm_revert`: restores the executor to the state stashed under `id`,
+    /// dropping `id` and every snapshot taken after it. Returns `false` if
+    /// `id` doesn't exist (already reverted past, or never taken).
+    pub async fn revert_to(&self, id: U256) -> bool
+    where
+        S: Clone,
+    {
+        match self.snapshots.write().await.revert(id) {
+            Some(state) => {
+                self.vm_mut().await.reset(state);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// `anvil_setBalance` / `hardhat_setBalance`.
+    pub async fn set_balance(&self, address: Address, balance: U256)
+    where
+        S: Clone + sputnik::backend::Backend + sputnik::backend::ApplyBackend,
+    {
+        let mut state = (*self.vm().await.state()).clone();
+        cheats::set_balance(&mut state, address, balance);
+        self.vm_mut().await.reset(state);
+    }
+
+    /// `anvil_setNonce` / `hardhat_setNonce`.
+    pub async fn set_nonce(&self, address: Address, nonce: U256)
+    where
+        S: Clone + sputnik::backend::Backend + sputnik::backend::ApplyBackend,
+    {
+        let mut state = (*self.vm().await.state()).clone();
+        cheats::set_nonce(&mut state, address, nonce);
+        self.vm_mut().await.reset(state);
+    }
+
+    /// `anvil_setCode` / `hardhat_setCode`.
+    pub async fn set_code(&self, address: Address, code: Bytes)
+    where
+        S: Clone + sputnik::backend::Backend + sputnik::backend::ApplyBackend,
+    {
+        let mut state = (*self.vm().await.state()).clone();
+        cheats::set_code(&mut state, address, code);
+        self.vm_mut().await.reset(state);
+    }
+
+    /// `anvil_setStorageAt` / `hardhat_setStorageAt`.
+    pub async fn set_storage_at(&self, address: Address, slot: H256, value: H256)
+    where
+        S: Clone + sputnik::backend::Backend + sputnik::backend::ApplyBackend,
+    {
+        let mut state = (*self.vm().await.state()).clone();
+        cheats::set_storage_at(&mut state, address, slot, value);
+        self.vm_mut().await.reset(state);
+    }
+
+    /// `evm_mine`: finalizes any queued `set_block_base_fee` control and
+    /// advances the fee model by one (empty) block. See
+    /// [`cheats::PendingBlock`] for why this doesn't yet touch Sputnik's own
+    /// block environment.
+    pub async fn mine(&self) {
+        let mut pending = self.pending_block.write().await;
+        let default_base_fee = self.vm().await.gas_price();
+        let gas_limit = self.vm().await.gas_limit();
+        self.fees
+            .mine(gas_limit, pending.next_base_fee.take(), default_base_fee);
+    }
+
+    /// `evm_setNextBlockTimestamp`: would queue the timestamp to use for the
+    /// next call to [`Forge::mine`].
+    ///
+    /// Unsupported: Sputnik's block environment (vicinity) is fixed at
+    /// executor construction, so there is no way to make the next block
+    /// actually observe a different timestamp yet (see
+    /// [`cheats::PendingBlock`]). Errors instead of silently accepting and
+    /// discarding the value, so callers aren't misled into thinking it took
+    /// effect.
+    pub async fn set_next_block_timestamp(
+        &self,
+        _timestamp: U256,
+    ) -> Result<(), <Self as Middleware>::Error> {
+        Err(eyre::eyre!(
+            "evm_setNextBlockTimestamp is not supported: this VM's block timestamp is fixed at executor construction"
+        )
+        .into())
+    }
+
+    /// `anvil_setNextBlockBaseFeePerGas`: queues the base fee to use for the
+    /// next call to [`Forge::mine`], overriding the 1559 recurrence.
+    pub async fn set_block_base_fee(&self, base_fee: U256) {
+        self.pending_block.write().await.next_base_fee = Some(base_fee);
+    }
+
+    // Thin aliases matching the JSON-RPC method names, for test harnesses
+    // that drive this crate over RPC (e.g. via a `JsonRpcClient` that
+    // dispatches into a `Forge` instead of a real node) rather than calling
+    // the methods above directly.
+    pub async fn evm_snapshot(&self) -> U256
+    where
+        S: Clone,
+    {
+        self.snapshot().await
+    }
+
+    pub async fn evm_revert(&self, id: U256) -> bool
+    where
+        S: Clone,
+    {
+        self.revert_to(id).await
+    }
+
+    pub async fn anvil_set_balance(&self, address: Address, balance: U256)
+    where
+        S: Clone + sputnik::backend::Backend + sputnik::backend::ApplyBackend,
+    {
+        self.set_balance(address, balance).await
+    }
+
+    pub async fn anvil_set_nonce(&self, address: Address, nonce: U256)
+    where
+        S: Clone + sputnik::backend::Backend + sputnik::backend::ApplyBackend,
+    {
+        self.set_nonce(address, nonce).await
+    }
+
+    pub async fn anvil_set_code(&self, address: Address, code: Bytes)
+    where
+        S: Clone + sputnik::backend::Backend + sputnik::backend::ApplyBackend,
+    {
+        self.set_code(address, code).await
+    }
+
+    pub async fn anvil_set_storage_at(&self, address: Address, slot: H256, value: H256)
+    where
+        S: Clone + sputnik::backend::Backend + sputnik::backend::ApplyBackend,
+    {
+        self.set_storage_at(address, slot, value).await
+    }
+
+    pub async fn evm_mine(&self) {
+        self.mine().await
+    }
+
+    pub async fn evm_set_next_block_timestamp(
+        &self,
+        timestamp: U256,
+    ) -> Result<(), <Self as Middleware>::Error> {
+        self.set_next_block_timestamp(timestamp).await
+    }
+
+    pub async fn anvil_set_next_block_base_fee_per_gas(&self, base_fee: U256) {
+        self.set_block_base_fee(base_fee).await
+    }
+
+    /// Mirrors `EntryPoint.simulateValidation(op)`: runs the op's validation
+    /// call_data against `entry_point` on the local EVM with a step listener
+    /// enforcing the ERC-4337 association rules, and reverts state
+    /// afterwards (same clone/reset trick as `call()`) so simulation never
+    /// mutates committed state.
+    pub async fn simulate_user_operation(
+        &self,
+        op: &UserOperation,
+        entry_point: Address,
+    ) -> Result<ValidationReport, <Self as Middleware>::Error> {
+        let state = (*self.vm().await.state()).clone();
+
+        let mut tracer = ValidationTracer::new(op.sender, !op.init_code.is_empty());
+        let data = op.simulate_validation_call_data();
+
+        let call_result = {
+            let mut vm = self.vm_mut().await;
+            evm_runtime::tracing::using(&mut tracer, || {
+                vm.call_raw(Address::zero(), entry_point, data, U256::zero(), false)
+            })
+        };
+
+        self.vm_mut().await.reset(state);
+
+        let (bytes, _exit, _gas, _logs) = call_result?;
+
+        // `simulateValidation` is specified to always revert - `bytes` here
+        // is whatever `call_raw` returned as the call's output, revert data
+        // included; decode it rather than trusting `exit`, since a plain
+        // `E::is_success` reading (as opposed to `ValidationResult`'s own
+        // `sigFailed` flag) would get this backwards on the happy path.
+        let outcome = decode_simulate_validation_revert(&bytes);
+
+        Ok(ValidationReport {
+            sig_failed: outcome.sig_failed,
+            valid_after: outcome.valid_after,
+            valid_until: outcome.valid_until,
+            pre_fund: outcome.pre_fund,
+            paymaster_context: outcome.paymaster_context,
+            accessed: tracer.accessed,
+            banned_opcodes: tracer.banned_opcodes,
+            out_of_scope_storage: tracer.out_of_scope,
+        })
+    }
+
     pub async fn is_latest(&self, id: BlockId) -> Result<bool, <Self as Middleware>::Error> {
         match id {
             BlockId::Hash(hash) => {
@@ -173,9 +517,11 @@ where
         }
     }
 
-    // Sputnik can provide hashes for any block it produced, but not the rest of the block data
+    // Sputnik can provide hashes for any block it produced, but not the rest of the block data.
+    // When this `Forge`'s executor was built via `Forge::new_forked`, `self.vm`'s backend is
+    // a `fork::ForkedBackend` and this transparently falls back to the inner provider for
+    // anything local state doesn't have; built any other way, it's still just local state.
     pub async fn get_block_hash(&self, num: U256) -> H256 {
-        // TODO: try to pull historical data if we get back default and have a provider
         self.vm().await.block_hash(num)
     }
 
@@ -0,0 +1,342 @@
+use ethers_core::{
+    abi::{encode, Token},
+    types::{Address, Bytes, H256, U256},
+};
+use evm_runtime::tracing::{Event as RuntimeEvent, Listener as RuntimeListener};
+
+use crate::opcodes;
+
+// `U256::as_usize` panics if the value doesn't fit; stack words read off a
+// `Step` event are raw attacker-controlled input, so convert defensively.
+fn checked_usize(value: U256) -> Option<usize> {
+    if value > U256::from(usize::MAX) {
+        None
+    } else {
+        Some(value.as_usize())
+    }
+}
+
+/// An ERC-4337 `UserOperation`, as passed to `EntryPoint.simulateValidation`.
+#[derive(Debug, Clone)]
+pub struct UserOperation {
+    pub sender: Address,
+    pub nonce: U256,
+    pub init_code: Bytes,
+    pub call_data: Bytes,
+    pub call_gas_limit: U256,
+    pub verification_gas_limit: U256,
+    pub pre_verification_gas: U256,
+    pub max_fee_per_gas: U256,
+    pub max_priority_fee_per_gas: U256,
+    pub paymaster_and_data: Bytes,
+    pub signature: Bytes,
+}
+
+impl UserOperation {
+    pub fn paymaster(&self) -> Option<Address> {
+        (self.paymaster_and_data.len() >= 20)
+            .then(|| Address::from_slice(&self.paymaster_and_data[..20]))
+    }
+
+    fn tuple(&self) -> Token {
+        Token::Tuple(vec![
+            Token::Address(self.sender),
+            Token::Uint(self.nonce),
+            Token::Bytes(self.init_code.to_vec()),
+            Token::Bytes(self.call_data.to_vec()),
+            Token::Uint(self.call_gas_limit),
+            Token::Uint(self.verification_gas_limit),
+            Token::Uint(self.pre_verification_gas),
+            Token::Uint(self.max_fee_per_gas),
+            Token::Uint(self.max_priority_fee_per_gas),
+            Token::Bytes(self.paymaster_and_data.to_vec()),
+            Token::Bytes(self.signature.to_vec()),
+        ])
+    }
+
+    /// Calldata for `EntryPoint.simulateValidation(UserOperation)`.
+    pub fn simulate_validation_call_data(&self) -> Bytes {
+        const SIG: &str = "simulateValidation((address,uint256,bytes,bytes,uint256,uint256,uint256,uint256,uint256,bytes,bytes))";
+        let mut data = ethers_core::utils::id(SIG).to_vec();
+        data.extend(encode(&[self.tuple()]));
+        data.into()
+    }
+}
+
+/// Opcodes EIP-4337 forbids during validation, because they make validation
+/// depend on chain state a bundler can't simulate stably across blocks.
+/// `CREATE`/`CREATE2` are handled separately: they're only allowed once, for
+/// the sender's own `initCode` deploy.
+const BANNED_OPCODES: &[&str] = &[
+    "GASPRICE",
+    "GASLIMIT",
+    "BLOCKHASH",
+    "NUMBER",
+    "TIMESTAMP",
+    "COINBASE",
+    "DIFFICULTY",
+    "PREVRANDAO",
+    "BASEFEE",
+    "SELFBALANCE",
+    "BALANCE",
+    "ORIGIN",
+];
+
+#[derive(Debug, Clone)]
+pub struct StorageAccess {
+    pub address: Address,
+    pub slot: H256,
+}
+
+/// Report returned from `Forge::simulate_user_operation`, mirroring
+/// `EntryPoint.simulateValidation`'s `ValidationResult` plus the bundler-side
+/// opcode/storage association checks the spec asks bundlers to run over the trace.
+#[derive(Debug, Clone, Default)]
+pub struct ValidationReport {
+    pub sig_failed: bool,
+    pub valid_after: U256,
+    pub valid_until: U256,
+    pub pre_fund: U256,
+    pub paymaster_context: Bytes,
+    pub accessed: Vec<StorageAccess>,
+    pub banned_opcodes: Vec<String>,
+    pub out_of_scope_storage: Vec<StorageAccess>,
+}
+
+impl ValidationReport {
+    /// Whether a bundler should accept this op: no failed signature, no
+    /// banned opcodes, and every touched slot is in the sender's own scope.
+    pub fn is_valid(&self) -> bool {
+        !self.sig_failed && self.banned_opcodes.is_empty() && self.out_of_scope_storage.is_empty()
+    }
+}
+
+/// The `EntryPoint`-reported subset of a [`ValidationReport`]: everything
+/// except the bundler-side opcode/storage checks, which come from
+/// [`ValidationTracer`] instead.
+pub(crate) struct EntryPointOutcome {
+    pub sig_failed: bool,
+    pub valid_after: U256,
+    pub valid_until: U256,
+    pub pre_fund: U256,
+    pub paymaster_context: Bytes,
+}
+
+impl Default for EntryPointOutcome {
+    // Anything we can't make sense of (wrong selector, truncated data, the
+    // op ran to completion with no revert at all) is treated as a failed
+    // validation, not an accept-by-default.
+    fn default() -> Self {
+        Self {
+            sig_failed: true,
+            valid_after: U256::zero(),
+            valid_until: U256::zero(),
+            pre_fund: U256::zero(),
+            paymaster_context: Bytes::default(),
+        }
+    }
+}
+
+/// Decodes the revert payload of `EntryPoint.simulateValidation`.
+///
+/// `simulateValidation` is specified to *always* revert - with
+/// `ValidationResult(...)` when validation succeeded (bundlers call it via
+/// `eth_call` and read the "successful failure"), or `FailedOp(uint256,string)`
+/// when it didn't. A return without a revert, or a revert with neither
+/// selector, isn't valid per the spec and is treated as a failed validation.
+pub(crate) fn decode_simulate_validation_revert(data: &[u8]) -> EntryPointOutcome {
+    use ethers_core::abi::{decode, ParamType};
+
+    if data.len() < 4 {
+        return EntryPointOutcome::default();
+    }
+    let (selector, body) = data.split_at(4);
+
+    let validation_result_selector = ethers_core::utils::id(
+        "ValidationResult((uint256,uint256,bool,uint48,uint48,bytes),(uint256,uint256),(uint256,uint256),(uint256,uint256))",
+    );
+    if selector == &validation_result_selector[..4] {
+        let stake_info = ParamType::Tuple(vec![ParamType::Uint(256), ParamType::Uint(256)]);
+        let return_info = ParamType::Tuple(vec![
+            ParamType::Uint(256),
+            ParamType::Uint(256),
+            ParamType::Bool,
+            ParamType::Uint(48),
+            ParamType::Uint(48),
+            ParamType::Bytes,
+        ]);
+        let types = [return_info, stake_info.clone(), stake_info.clone(), stake_info];
+
+        let decoded = decode(&types, body).ok().and_then(|tokens| {
+            let Token::Tuple(return_info) = tokens.into_iter().next()? else {
+                return None;
+            };
+            let pre_fund = return_info.get(1)?.clone().into_uint()?;
+            let sig_failed = return_info.get(2)?.clone().into_bool()?;
+            let valid_after = return_info.get(3)?.clone().into_uint()?;
+            let valid_until = return_info.get(4)?.clone().into_uint()?;
+            let paymaster_context = return_info.get(5)?.clone().into_bytes()?;
+            Some(EntryPointOutcome {
+                sig_failed,
+                valid_after,
+                valid_until,
+                pre_fund,
+                paymaster_context: paymaster_context.into(),
+            })
+        });
+        return decoded.unwrap_or_default();
+    }
+
+    EntryPointOutcome::default()
+}
+
+/// Installs as a sputnik step listener for the duration of
+/// `simulateValidation` and enforces the EIP-4337 association rules as it
+/// goes: records every banned opcode hit and every storage access outside
+/// the sender's own account or slots that look like they're keyed by the
+/// sender's address.
+pub(crate) struct ValidationTracer {
+    sender: Address,
+    allow_create: bool,
+    created: bool,
+    // Every KECCAK256 result seen so far whose preimage was `abi.encode(sender, baseSlot)`
+    // for some 32-byte `baseSlot` - i.e. a `mapping(address => ...)` slot keyed by `sender`.
+    sender_keyed_slots: std::collections::HashSet<H256>,
+    pub banned_opcodes: Vec<String>,
+    pub accessed: Vec<StorageAccess>,
+    pub out_of_scope: Vec<StorageAccess>,
+}
+
+impl ValidationTracer {
+    pub fn new(sender: Address, has_init_code: bool) -> Self {
+        Self {
+            sender,
+            allow_create: has_init_code,
+            created: false,
+            sender_keyed_slots: std::collections::HashSet::new(),
+            banned_opcodes: Vec::new(),
+            accessed: Vec::new(),
+            out_of_scope: Vec::new(),
+        }
+    }
+
+    // KECCAK256 reads `size` bytes of already-written memory starting at
+    // `offset` and hashes them; since that memory is visible on the `Step`
+    // event fired just before the opcode runs, we can compute the same hash
+    // ourselves rather than wait for it to come back on the stack. Solidity
+    // lays out a `mapping(address => T)` slot as
+    // `keccak256(abi.encode(key, baseSlot))` - 64 bytes, key first - so a
+    // 64-byte preimage whose first word is `sender` (left-padded) marks the
+    // resulting hash as one of sender's own mapping slots.
+    fn note_keccak_preimage(&mut self, preimage: &[u8]) {
+        if preimage.len() != 64 {
+            return;
+        }
+        let key_word = &preimage[..32];
+        let is_sender_key = key_word[..12].iter().all(|b| *b == 0) && key_word[12..] == self.sender.0;
+        if is_sender_key {
+            let hash = H256::from(ethers_core::utils::keccak256(preimage));
+            self.sender_keyed_slots.insert(hash);
+        }
+    }
+
+    fn note_storage(&mut self, address: Address, slot: H256) {
+        // Associated storage per EIP-4337: the sender's own slots, or slots
+        // keyed by the sender's address - either directly (`slot == sender`,
+        // left-padded) or via a `mapping(address => ...)` whose slot is
+        // `keccak256(abi.encode(sender, baseSlot))`, tracked via
+        // `note_keccak_preimage` as KECCAK256 ops are observed.
+        let keyed_by_sender = H256::from(self.sender) == slot || self.sender_keyed_slots.contains(&slot);
+        if address != self.sender && !keyed_by_sender {
+            self.out_of_scope.push(StorageAccess { address, slot });
+        }
+        self.accessed.push(StorageAccess { address, slot });
+    }
+}
+
+impl RuntimeListener for ValidationTracer {
+    fn event(&mut self, event: RuntimeEvent) {
+        match event {
+            RuntimeEvent::Step {
+                opcode,
+                stack,
+                memory,
+                ..
+            } => {
+                let op = opcodes::name(opcode.0);
+                if op == "KECCAK256" {
+                    let words = stack.data();
+                    let len = words.len();
+                    if len >= 2 {
+                        // offset/size are attacker-controlled stack words; a
+                        // contract can push a value larger than `usize::MAX`
+                        // (which would just revert the real opcode) so bail
+                        // rather than panicking in `as_usize()`.
+                        if let (Some(offset), Some(size)) = (
+                            checked_usize(U256::from_big_endian(&words[len - 1].0)),
+                            checked_usize(U256::from_big_endian(&words[len - 2].0)),
+                        ) {
+                            let mem = memory.data();
+                            if offset.saturating_add(size) <= mem.len() {
+                                self.note_keccak_preimage(&mem[offset..offset + size]);
+                            }
+                        }
+                    }
+                } else if op == "CREATE" || op == "CREATE2" {
+                    if self.allow_create && !self.created {
+                        self.created = true;
+                    } else {
+                        self.banned_opcodes.push(op.to_string());
+                    }
+                } else if BANNED_OPCODES.contains(&op) {
+                    self.banned_opcodes.push(op.to_string());
+                }
+            }
+            RuntimeEvent::SLoad { address, index, .. } => self.note_storage(address, index),
+            RuntimeEvent::SStore { address, index, .. } => self.note_storage(address, index),
+            RuntimeEvent::StepResult { .. } => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // This is the regression the bundler-side checks actually depend on: if
+    // opcode names ever went back to coming from `Opcode`'s `Debug` impl
+    // (e.g. `"Opcode(58)"` for a bare newtype) rather than `opcodes::name`,
+    // every banned-opcode comparison below would silently stop matching and
+    // `ValidationTracer` would wave every op through.
+    #[test]
+    fn gasprice_opcode_is_flagged_as_banned() {
+        let op = opcodes::name(0x3a);
+        assert_eq!(op, "GASPRICE");
+        assert!(BANNED_OPCODES.contains(&op));
+    }
+
+    #[test]
+    fn mapping_slot_keyed_by_sender_is_not_out_of_scope() {
+        let sender: Address = "0x0000000000000000000000000000000000000001"
+            .parse()
+            .unwrap();
+        let mut tracer = ValidationTracer::new(sender, false);
+
+        let mut preimage = [0u8; 64];
+        preimage[12..32].copy_from_slice(&sender.0);
+        let base_slot = H256::repeat_byte(0x07);
+        preimage[32..64].copy_from_slice(base_slot.as_bytes());
+        let slot = H256::from(ethers_core::utils::keccak256(preimage));
+
+        tracer.note_keccak_preimage(&preimage);
+        // Some other contract's storage, but at a slot derived from sender's
+        // own address - e.g. a `mapping(address => uint256) balances` read.
+        let other_contract: Address = "0x0000000000000000000000000000000000000002"
+            .parse()
+            .unwrap();
+        tracer.note_storage(other_contract, slot);
+
+        assert!(tracer.out_of_scope.is_empty());
+        assert_eq!(tracer.accessed.len(), 1);
+    }
+}
@@ -0,0 +1,153 @@
+use std::sync::RwLock;
+
+use ethers_core::types::{FeeHistory, U256};
+
+#[derive(Clone, Debug)]
+struct BlockFeeInfo {
+    base_fee: U256,
+    gas_used: U256,
+    gas_limit: U256,
+    // effective priority fee paid by every tx executed as part of this block
+    rewards: Vec<U256>,
+}
+
+/// Tracks a base fee per produced block using the EIP-1559 recurrence and
+/// serves `eth_feeHistory` off of it.
+///
+/// This crate doesn't batch transactions into blocks, so every committed
+/// transaction is treated as the sole transaction of its own block.
+#[derive(Default)]
+pub struct FeeModel {
+    blocks: RwLock<Vec<BlockFeeInfo>>,
+}
+
+impl FeeModel {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn current_base_fee(&self, default_base_fee: U256) -> U256 {
+        self.blocks
+            .read()
+            .unwrap()
+            .last()
+            .map(|b| b.base_fee)
+            .unwrap_or(default_base_fee)
+    }
+
+    // Pushes an empty block (0 gas used), either deriving its base fee from
+    // the 1559 recurrence or, if `override_base_fee` is set (from
+    // `anvil_setNextBlockBaseFeePerGas`), using that value directly.
+    pub fn mine(&self, gas_limit: U256, override_base_fee: Option<U256>, default_base_fee: U256) {
+        let mut blocks = self.blocks.write().unwrap();
+        let base_fee = override_base_fee.unwrap_or_else(|| {
+            blocks
+                .last()
+                .map(|b| next_base_fee(b.base_fee, b.gas_used, b.gas_limit / 2))
+                .unwrap_or(default_base_fee)
+        });
+        blocks.push(BlockFeeInfo {
+            base_fee,
+            gas_used: U256::zero(),
+            gas_limit,
+            rewards: Vec::new(),
+        });
+    }
+
+    pub fn record_tx(&self, default_base_fee: U256, gas_limit: U256, gas_used: U256, priority_fee: U256) {
+        let mut blocks = self.blocks.write().unwrap();
+        // A block's base fee follows from its parent's base fee and gas used.
+        let base_fee = blocks
+            .last()
+            .map(|b| next_base_fee(b.base_fee, b.gas_used, b.gas_limit / 2))
+            .unwrap_or(default_base_fee);
+        blocks.push(BlockFeeInfo {
+            base_fee,
+            gas_used,
+            gas_limit,
+            rewards: vec![priority_fee],
+        });
+    }
+
+    pub fn fee_history(
+        &self,
+        block_count: U256,
+        newest_block: U256,
+        reward_percentiles: &[f64],
+        default_base_fee: U256,
+    ) -> FeeHistory {
+        let blocks = self.blocks.read().unwrap();
+        if blocks.is_empty() {
+            return FeeHistory {
+                oldest_block: U256::zero(),
+                base_fee_per_gas: vec![default_base_fee],
+                gas_used_ratio: Vec::new(),
+                reward: Vec::new(),
+            };
+        }
+
+        let newest = newest_block.as_usize().min(blocks.len() - 1);
+        let count = block_count.as_usize().min(newest + 1).max(1);
+        let oldest = newest + 1 - count;
+
+        let mut base_fee_per_gas = Vec::with_capacity(count + 1);
+        let mut gas_used_ratio = Vec::with_capacity(count);
+        let mut reward = Vec::with_capacity(count);
+
+        for info in &blocks[oldest..=newest] {
+            base_fee_per_gas.push(info.base_fee);
+            gas_used_ratio.push(
+                info.gas_used.as_u64() as f64 / info.gas_limit.as_u64().max(1) as f64,
+            );
+            reward.push(percentiles(&info.rewards, reward_percentiles));
+        }
+
+        // `base_fee_per_gas` always has one extra entry: the projected base
+        // fee of the block after `newest_block`.
+        let last = &blocks[newest];
+        let next = blocks
+            .get(newest + 1)
+            .map(|b| b.base_fee)
+            .unwrap_or_else(|| next_base_fee(last.base_fee, last.gas_used, last.gas_limit / 2));
+        base_fee_per_gas.push(next);
+
+        FeeHistory {
+            oldest_block: (oldest as u64).into(),
+            base_fee_per_gas,
+            gas_used_ratio,
+            reward,
+        }
+    }
+}
+
+// gas_target = gas_limit / 2; unchanged at target, otherwise nudges by up to
+// 1/8th of the base fee in the direction gas usage deviated from target.
+fn next_base_fee(base_fee: U256, gas_used: U256, gas_target: U256) -> U256 {
+    if gas_target.is_zero() || gas_used == gas_target {
+        return base_fee;
+    }
+    if gas_used > gas_target {
+        let delta = gas_used - gas_target;
+        let change = std::cmp::max(U256::one(), base_fee * delta / gas_target / 8);
+        base_fee + change
+    } else {
+        let delta = gas_target - gas_used;
+        let change = base_fee * delta / gas_target / 8;
+        base_fee.saturating_sub(change)
+    }
+}
+
+fn percentiles(rewards: &[U256], percentiles: &[f64]) -> Vec<U256> {
+    if rewards.is_empty() {
+        return percentiles.iter().map(|_| U256::zero()).collect();
+    }
+    let mut sorted = rewards.to_vec();
+    sorted.sort();
+    percentiles
+        .iter()
+        .map(|p| {
+            let idx = (((p / 100.0) * sorted.len() as f64) as usize).min(sorted.len() - 1);
+            sorted[idx]
+        })
+        .collect()
+}
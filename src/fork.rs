@@ -0,0 +1,253 @@
+use std::{collections::HashMap, future::Future, sync::Mutex};
+
+use ethers_core::types::{Address, BlockId, BlockNumber, H256, U256};
+use ethers_providers::Middleware;
+use sputnik::backend::{Backend, Basic};
+
+use crate::core::Inner;
+
+#[derive(Default)]
+struct Cache {
+    basic: HashMap<Address, Basic>,
+    code: HashMap<Address, Vec<u8>>,
+    storage: HashMap<(Address, H256), H256>,
+    block_hash: HashMap<U256, H256>,
+}
+
+/// A [`Backend`] that lazily forks off an inner JSON-RPC provider.
+///
+/// Reads are served from `local` first. When `local` comes back with the
+/// default value (the usual Sputnik signal for "I don't have this"), and
+/// an inner provider is actually wired up via [`Inner::Use`], the value is
+/// instead fetched from `inner` as of `fork_block` and cached so the same
+/// account/slot/block-hash is never fetched twice.
+pub struct ForkedBackend<S, M> {
+    local: S,
+    inner: Inner<M>,
+    fork_block: U256,
+    cache: Mutex<Cache>,
+}
+
+impl<S, M> ForkedBackend<S, M> {
+    pub fn new(local: S, inner: Inner<M>, fork_block: U256) -> Self {
+        Self {
+            local,
+            inner,
+            fork_block,
+            cache: Mutex::new(Cache::default()),
+        }
+    }
+
+    pub fn local(&self) -> &S {
+        &self.local
+    }
+
+    pub fn fork_block(&self) -> U256 {
+        self.fork_block
+    }
+}
+
+impl<S, M> ForkedBackend<S, M>
+where
+    M: Middleware + Clone + 'static,
+{
+    // `Backend` is a sync trait (Sputnik steps the EVM synchronously), so
+    // there's no way to thread an `.await` through `basic`/`code`/`storage`/
+    // `block_hash`. Block on the inner future instead, the same way
+    // `Forge::call` has to clone/reset state synchronously around an
+    // otherwise-async executor.
+    //
+    // `tokio::task::block_in_place` panics outside a multi-thread runtime
+    // (e.g. the default `#[tokio::test]` flavor), so only take that path
+    // when we know it's safe; otherwise drive the future to completion on a
+    // throwaway current-thread runtime on its own OS thread - which, since
+    // it outlives this call, needs a future that owns its data rather than
+    // borrowing `self.inner.get()`. Callers build `fut` from a cloned
+    // provider for exactly that reason; see `basic`/`code`/`storage`/`block_hash` below.
+    fn block_on<F>(&self, fut: F) -> F::Output
+    where
+        F: Future + Send + 'static,
+        F::Output: Send + 'static,
+    {
+        match tokio::runtime::Handle::try_current() {
+            Ok(handle) if handle.runtime_flavor() == tokio::runtime::RuntimeFlavor::MultiThread => {
+                tokio::task::block_in_place(|| handle.block_on(fut))
+            }
+            _ => std::thread::spawn(move || {
+                tokio::runtime::Builder::new_current_thread()
+                    .enable_all()
+                    .build()
+                    .expect("failed to start fork-fetch runtime")
+                    .block_on(fut)
+            })
+            .join()
+            .expect("fork-fetch thread panicked"),
+        }
+    }
+
+    fn fork_block_id(&self) -> BlockId {
+        BlockId::Number(BlockNumber::Number(self.fork_block.as_u64().into()))
+    }
+}
+
+impl<S, M> Backend for ForkedBackend<S, M>
+where
+    S: Backend,
+    M: Middleware + Clone + 'static,
+{
+    fn gas_price(&self) -> U256 {
+        self.local.gas_price()
+    }
+    fn origin(&self) -> Address {
+        self.local.origin()
+    }
+    fn block_number(&self) -> U256 {
+        self.local.block_number()
+    }
+    fn block_coinbase(&self) -> Address {
+        self.local.block_coinbase()
+    }
+    fn block_timestamp(&self) -> U256 {
+        self.local.block_timestamp()
+    }
+    fn block_difficulty(&self) -> U256 {
+        self.local.block_difficulty()
+    }
+    fn block_gas_limit(&self) -> U256 {
+        self.local.block_gas_limit()
+    }
+    fn block_base_fee_per_gas(&self) -> U256 {
+        self.local.block_base_fee_per_gas()
+    }
+    fn chain_id(&self) -> U256 {
+        self.local.chain_id()
+    }
+    fn exists(&self, address: Address) -> bool {
+        self.local.exists(address) || self.inner.is_use()
+    }
+
+    fn block_hash(&self, number: U256) -> H256 {
+        let local = self.local.block_hash(number);
+        if local != Default::default() || self.inner.is_not() {
+            return local;
+        }
+        if let Some(hash) = self.cache.lock().unwrap().block_hash.get(&number) {
+            return *hash;
+        }
+        let id = BlockId::Number(BlockNumber::Number(number.as_u64().into()));
+        let provider = self.inner.get().clone();
+        let hash = self
+            .block_on(async move { provider.get_block(id).await })
+            .ok()
+            .flatten()
+            .and_then(|b| b.hash)
+            .unwrap_or_default();
+        self.cache.lock().unwrap().block_hash.insert(number, hash);
+        hash
+    }
+
+    fn basic(&self, address: Address) -> Basic {
+        if let Some(basic) = self.cache.lock().unwrap().basic.get(&address) {
+            return basic.clone();
+        }
+        let local = self.local.basic(address);
+        if local.balance != U256::zero() || local.nonce != U256::zero() || self.inner.is_not() {
+            return local;
+        }
+        let id = self.fork_block_id();
+        let balance_provider = self.inner.get().clone();
+        let nonce_provider = self.inner.get().clone();
+        let basic = Basic {
+            balance: self
+                .block_on(async move { balance_provider.get_balance(address, Some(id)).await })
+                .unwrap_or_default(),
+            nonce: self
+                .block_on(async move { nonce_provider.get_transaction_count(address, Some(id)).await })
+                .unwrap_or_default(),
+        };
+        self.cache
+            .lock()
+            .unwrap()
+            .basic
+            .insert(address, basic.clone());
+        basic
+    }
+
+    fn code(&self, address: Address) -> Vec<u8> {
+        if let Some(code) = self.cache.lock().unwrap().code.get(&address) {
+            return code.clone();
+        }
+        let local = self.local.code(address);
+        if !local.is_empty() || self.inner.is_not() {
+            return local;
+        }
+        let id = self.fork_block_id();
+        let provider = self.inner.get().clone();
+        let code = self
+            .block_on(async move { provider.get_code(address, Some(id)).await })
+            .map(|b| b.to_vec())
+            .unwrap_or_default();
+        self.cache
+            .lock()
+            .unwrap()
+            .code
+            .insert(address, code.clone());
+        code
+    }
+
+    fn storage(&self, address: Address, index: H256) -> H256 {
+        if let Some(val) = self.cache.lock().unwrap().storage.get(&(address, index)) {
+            return *val;
+        }
+        let local = self.local.storage(address, index);
+        if local != H256::zero() || self.inner.is_not() {
+            return local;
+        }
+        let id = self.fork_block_id();
+        let provider = self.inner.get().clone();
+        let val = self
+            .block_on(async move { provider.get_storage_at(address, index, Some(id)).await })
+            .unwrap_or_default();
+        self.cache
+            .lock()
+            .unwrap()
+            .storage
+            .insert((address, index), val);
+        val
+    }
+
+    fn original_storage(&self, address: Address, index: H256) -> Option<H256> {
+        Some(self.storage(address, index))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ethers_core::types::{Block, TxHash};
+    use ethers_providers::Provider;
+    use sputnik::backend::MemoryBackend;
+
+    // Uses the default (current-thread) `#[tokio::test]` runtime on purpose:
+    // this is exactly the flavor `block_in_place` would panic on, so it also
+    // exercises the fallback path in `ForkedBackend::block_on`.
+    #[tokio::test]
+    async fn hydrates_missing_block_hash_from_inner_provider() {
+        let (provider, mock) = Provider::mocked();
+        let expected_hash = H256::repeat_byte(0xab);
+        let block = Block::<TxHash> {
+            hash: Some(expected_hash),
+            ..Default::default()
+        };
+        mock.push(block).unwrap();
+
+        let vicinity = Default::default();
+        let local = MemoryBackend::new(&vicinity, Default::default());
+        let forked = ForkedBackend::new(local, Inner::Use(provider), U256::one());
+
+        // `local` never produced block 1, so this has to come from `inner`.
+        assert_eq!(forked.block_hash(U256::one()), expected_hash);
+        // Second read must be served from the cache, not a (now-empty) mock queue.
+        assert_eq!(forked.block_hash(U256::one()), expected_hash);
+    }
+}
@@ -1,6 +1,13 @@
 pub mod core;
 pub mod evm;
+pub mod cheats;
+pub mod erc4337;
+pub mod fees;
+pub mod fork;
+pub mod logs;
 pub mod middleware;
+pub mod opcodes;
+pub mod trace;
 
 #[cfg(test)]
 mod tests {
@@ -0,0 +1,280 @@
+use std::{
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        RwLock,
+    },
+};
+
+use ethers_core::types::{Address, Bloom, Filter, FilterBlockOption, Log, ValueOrArray, H256, U256, U64};
+use ethers_core::utils::keccak256;
+use evm_runtime::tracing::{Event as RuntimeEvent, Listener as RuntimeListener};
+
+use crate::opcodes;
+
+// `U256::as_usize` panics if the value doesn't fit; stack words read off a
+// `Step` event are raw attacker-controlled input, so convert defensively.
+fn checked_usize(value: U256) -> Option<usize> {
+    if value > U256::from(usize::MAX) {
+        None
+    } else {
+        Some(value.as_usize())
+    }
+}
+
+#[derive(Default)]
+struct LogCapture {
+    logs: Vec<Log>,
+}
+
+impl RuntimeListener for LogCapture {
+    fn event(&mut self, event: RuntimeEvent) {
+        let RuntimeEvent::Step {
+            opcode,
+            context,
+            stack,
+            memory,
+            ..
+        } = event
+        else {
+            return;
+        };
+
+        let n_topics = match opcodes::name(opcode.0) {
+            "LOG0" => 0,
+            "LOG1" => 1,
+            "LOG2" => 2,
+            "LOG3" => 3,
+            "LOG4" => 4,
+            _ => return,
+        };
+
+        // Stack layout before a LOGn executes (top first): offset, size,
+        // then `n_topics` topic words - the same layout the opcode itself
+        // consumes, so reading it here needs no decoding at all.
+        let words = stack.data();
+        let len = words.len();
+        if len < 2 + n_topics {
+            return;
+        }
+        // offset/size are attacker-controlled stack words; a contract can
+        // push a value larger than `usize::MAX` (which would just revert the
+        // real opcode) so bail rather than panicking in `as_usize()`.
+        let (Some(offset), Some(size)) = (
+            checked_usize(U256::from_big_endian(&words[len - 1].0)),
+            checked_usize(U256::from_big_endian(&words[len - 2].0)),
+        ) else {
+            return;
+        };
+        let topics: Vec<H256> = (0..n_topics).map(|i| H256(words[len - 3 - i].0)).collect();
+
+        let mem = memory.data();
+        if offset.saturating_add(size) > mem.len() {
+            return;
+        }
+        let data = mem[offset..offset + size].to_vec();
+
+        self.logs.push(Log {
+            address: context.address,
+            topics,
+            data: data.into(),
+            ..Default::default()
+        });
+    }
+}
+
+/// Runs `f` (a `call_raw`/`deploy`) with a step listener installed that
+/// reads every `LOGn` directly off the stack/memory as it executes, and
+/// hands back `f`'s result alongside the logs it emitted as real
+/// `ethers_core::types::Log`s - not the best-effort `Vec<String>` `Evm::call_raw`
+/// also returns, which loses topic structure the moment it's formatted as text.
+pub fn capture_logs<F, R>(f: F) -> (R, Vec<Log>)
+where
+    F: FnOnce() -> R,
+{
+    let mut capture = LogCapture::default();
+    let out = evm_runtime::tracing::using(&mut capture, f);
+    (out, capture.logs)
+}
+
+/// Computes the standard 2048-bit, 3-hash Ethereum logs bloom for a set of logs.
+pub fn logs_bloom<'a>(logs: impl IntoIterator<Item = &'a Log>) -> Bloom {
+    let mut bloom = Bloom::default();
+    for log in logs {
+        accrue_bloom(&mut bloom, log.address.as_bytes());
+        for topic in &log.topics {
+            accrue_bloom(&mut bloom, topic.as_bytes());
+        }
+    }
+    bloom
+}
+
+fn accrue_bloom(bloom: &mut Bloom, input: &[u8]) {
+    let hash = keccak256(input);
+    for i in &[0usize, 2, 4] {
+        let bit = (((hash[*i] as usize) << 8) + hash[*i + 1] as usize) & 0x7ff;
+        let byte_index = 255 - bit / 8;
+        let bit_index = bit % 8;
+        bloom.0[byte_index] |= 1 << bit_index;
+    }
+}
+
+struct Entry {
+    log: Log,
+    block_number: U64,
+}
+
+struct WatchedFilter {
+    filter: Filter,
+    last_seen: usize,
+}
+
+/// Append-only index of every log emitted by `Forge` since it was created,
+/// so `get_logs`/`new_filter`/`get_filter_changes` can serve event watchers
+/// without a real node behind them.
+#[derive(Default)]
+pub struct LogIndex {
+    next_id: AtomicU64,
+    entries: RwLock<Vec<Entry>>,
+    filters: RwLock<HashMap<U256, WatchedFilter>>,
+}
+
+impl LogIndex {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn push(&self, block_number: U64, logs: Vec<Log>) {
+        let mut entries = self.entries.write().unwrap();
+        let tx_index = entries.len();
+        for (i, mut log) in logs.into_iter().enumerate() {
+            log.block_number = Some(block_number);
+            log.log_index = Some((tx_index + i).into());
+            entries.push(Entry { log, block_number });
+        }
+    }
+
+    pub fn get_logs(&self, filter: &Filter) -> Vec<Log> {
+        self.entries
+            .read()
+            .unwrap()
+            .iter()
+            .filter(|e| matches(filter, e))
+            .map(|e| e.log.clone())
+            .collect()
+    }
+
+    pub fn new_filter(&self, filter: Filter) -> U256 {
+        let id = U256::from(self.next_id.fetch_add(1, Ordering::SeqCst));
+        let last_seen = self.entries.read().unwrap().len();
+        self.filters
+            .write()
+            .unwrap()
+            .insert(id, WatchedFilter { filter, last_seen });
+        id
+    }
+
+    pub fn uninstall_filter(&self, id: U256) -> bool {
+        self.filters.write().unwrap().remove(&id).is_some()
+    }
+
+    pub fn filter_changes(&self, id: U256) -> Vec<Log> {
+        let entries = self.entries.read().unwrap();
+        let mut filters = self.filters.write().unwrap();
+        let Some(state) = filters.get_mut(&id) else {
+            return Vec::new();
+        };
+        let changes = entries[state.last_seen..]
+            .iter()
+            .filter(|e| matches(&state.filter, e))
+            .map(|e| e.log.clone())
+            .collect();
+        state.last_seen = entries.len();
+        changes
+    }
+}
+
+fn matches(filter: &Filter, entry: &Entry) -> bool {
+    match &filter.block_option {
+        FilterBlockOption::Range { from_block, to_block } => {
+            if let Some(from) = from_block {
+                if entry.block_number < from.as_number().unwrap_or_default() {
+                    return false;
+                }
+            }
+            if let Some(to) = to_block {
+                if let Some(to) = to.as_number() {
+                    if entry.block_number > to {
+                        return false;
+                    }
+                }
+            }
+        }
+        FilterBlockOption::AtBlockHash(_) => {} // we don't index by hash; treat as unfiltered by block
+    }
+
+    if let Some(addresses) = &filter.address {
+        let matched = match addresses {
+            ValueOrArray::Value(addr) => entry.log.address == *addr,
+            ValueOrArray::Array(addrs) => addrs.contains(&entry.log.address),
+        };
+        if !matched {
+            return false;
+        }
+    }
+
+    for (i, topic_filter) in filter.topics.iter().enumerate() {
+        let Some(topic_filter) = topic_filter else { continue };
+        let wanted: Vec<H256> = match topic_filter {
+            ValueOrArray::Value(Some(t)) => vec![*t],
+            ValueOrArray::Array(ts) => ts.iter().flatten().copied().collect(),
+            _ => continue,
+        };
+        match entry.log.topics.get(i) {
+            Some(got) if wanted.contains(got) => {}
+            _ => return false,
+        }
+    }
+
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // This is exactly the case a `Vec<String>` of formatted logs could never
+    // reliably serve: a real event topic (e.g. `Transfer`'s signature hash)
+    // used to filter `get_logs`/`eth_getFilterChanges`.
+    #[test]
+    fn get_logs_filters_by_topic() {
+        let index = LogIndex::new();
+        let emitter: Address = "0x0000000000000000000000000000000000000042"
+            .parse()
+            .unwrap();
+        let transfer_topic = H256::from(keccak256("Transfer(address,address,uint256)"));
+        let other_topic = H256::repeat_byte(0x11);
+
+        index.push(
+            U64::one(),
+            vec![
+                Log {
+                    address: emitter,
+                    topics: vec![transfer_topic],
+                    ..Default::default()
+                },
+                Log {
+                    address: emitter,
+                    topics: vec![other_topic],
+                    ..Default::default()
+                },
+            ],
+        );
+
+        let filter = Filter::new().topic0(transfer_topic);
+        let matched = index.get_logs(&filter);
+
+        assert_eq!(matched.len(), 1);
+        assert_eq!(matched[0].topics, vec![transfer_topic]);
+    }
+}
@@ -1,7 +1,7 @@
 use async_trait::async_trait;
 use ethers_core::types::{
-    transaction::eip2718::TypedTransaction, Block, BlockId, Bytes, NameOrAddress,
-    TransactionReceipt, TxHash, U256, U64,
+    transaction::eip2718::TypedTransaction, Block, BlockId, BlockTrace, Bytes, NameOrAddress,
+    TraceType, TransactionReceipt, TxHash, U256, U64,
 };
 use ethers_providers::{
     maybe, FromErr, Middleware, PendingTransaction, PendingTxState, ProviderError,
@@ -13,6 +13,7 @@ use thiserror::Error;
 use crate::{
     core::{Forge, Inner, TxOutput},
     evm::VmShow,
+    trace,
 };
 
 #[derive(Error, Debug)]
@@ -54,12 +55,26 @@ where
     }
 }
 
+// The effective priority fee a tx is paying on top of `base_fee`, used to
+// populate `eth_feeHistory`'s reward matrix.
+fn priority_fee(tx: &TypedTransaction, base_fee: U256) -> U256 {
+    match tx {
+        TypedTransaction::Legacy(t) => t.gas_price.unwrap_or_default().saturating_sub(base_fee),
+        TypedTransaction::Eip2930(t) => t.tx.gas_price.unwrap_or_default().saturating_sub(base_fee),
+        TypedTransaction::Eip1559(t) => {
+            let max_fee = t.max_fee_per_gas.unwrap_or_default();
+            let max_priority = t.max_priority_fee_per_gas.unwrap_or_default();
+            std::cmp::min(max_priority, max_fee.saturating_sub(base_fee))
+        }
+    }
+}
+
 #[async_trait]
 impl<M, E, S> Middleware for Forge<M, E, S>
 where
     M: Middleware,
     E: Evm<S> + VmShow + Send + Sync,
-    S: Clone + Send + Sync + Debug,
+    S: sputnik::backend::Backend + Clone + Send + Sync + Debug,
     E::ReturnReason: Send,
 {
     type Error = ForgeError<M>;
@@ -75,7 +90,25 @@ where
     }
 
     async fn get_gas_price(&self) -> Result<U256, Self::Error> {
-        Ok(self.vm().await.gas_price())
+        Ok(self.fees.current_base_fee(self.vm().await.gas_price()))
+    }
+
+    async fn fee_history<T: Into<U256> + Send + Sync>(
+        &self,
+        block_count: T,
+        newest_block: ethers_core::types::BlockNumber,
+        reward_percentiles: &[f64],
+    ) -> Result<ethers_core::types::FeeHistory, Self::Error> {
+        let newest_block = match newest_block {
+            ethers_core::types::BlockNumber::Number(n) => n.as_u64().into(),
+            _ => self.vm().await.block_number(),
+        };
+        Ok(self.fees.fee_history(
+            block_count.into(),
+            newest_block,
+            reward_percentiles,
+            self.vm().await.gas_price(),
+        ))
     }
 
     async fn get_block_number(&self) -> Result<U64, Self::Error> {
@@ -143,11 +176,34 @@ where
         // hash modulo signature, which we may not have
         let transaction_hash = tx.sighash();
 
+        // Stamp the structured logs `apply_tx` already captured with this
+        // tx's hash/block, record them in the append-only log index so
+        // get_logs/new_filter can see them later, and attach them (plus the
+        // derived bloom) to the receipt like a real node would.
+        let block_number = self.get_block_number().await?;
+        let mut logs = res.logs.clone();
+        for log in &mut logs {
+            log.transaction_hash = Some(transaction_hash);
+            log.block_number = Some(block_number);
+        }
+        let logs_bloom = crate::logs::logs_bloom(&logs);
+        self.logs.push(block_number, logs.clone());
+
+        let base_fee = self.vm().await.gas_price();
+        self.fees.record_tx(
+            base_fee,
+            self.vm().await.gas_limit(),
+            res.gas.into(),
+            priority_fee(&tx, base_fee),
+        );
+
         let receipt = TransactionReceipt {
             gas_used,
             status,
             contract_address,
             transaction_hash,
+            logs,
+            logs_bloom,
             ..Default::default()
         };
 
@@ -180,6 +236,78 @@ where
         Ok(bytes)
     }
 
+    // Runs the tx against the local VM with a step listener attached and
+    // reports it back through ethers' parity-style tracing surface, same as
+    // a real node would for `trace_call`. `StateDiff` is populated from the
+    // pre/post state clones below; `VmTrace` isn't supported yet and errors
+    // if requested (see `trace::to_block_trace`).
+    async fn trace_call<T: Into<TypedTransaction> + Send + Sync>(
+        &self,
+        req: T,
+        trace_type: Vec<TraceType>,
+        block: Option<BlockId>,
+    ) -> Result<BlockTrace, Self::Error> {
+        let mut tx = req.into();
+        self.fill_transaction(&mut tx, block).await?;
+
+        // Same clone/reset trick `call()` uses: tracing must not mutate
+        // committed state. Keep a pre-call clone to diff against and to
+        // restore, and take a post-call clone before resetting so `StateDiff`
+        // has both snapshots to compare.
+        let pre = (*self.vm().await.state()).clone();
+
+        let (_res, tracer) = self.apply_tx_traced(&tx).await?;
+        let post = (*self.vm().await.state()).clone();
+        let diff = trace_type
+            .contains(&TraceType::StateDiff)
+            .then(|| trace::state_diff(&pre, &post, tracer.touched()));
+        let frame = tracer.into_call_frame();
+
+        self.vm_mut().await.reset(pre);
+
+        let trace = trace::to_block_trace(frame.as_ref(), &trace_type, diff)
+            .map_err(eyre::Report::from)?;
+        Ok(trace)
+    }
+
+    async fn get_logs(&self, filter: &ethers_core::types::Filter) -> Result<Vec<ethers_core::types::Log>, Self::Error> {
+        Ok(self.logs.get_logs(filter))
+    }
+
+    async fn new_filter(
+        &self,
+        filter: ethers_providers::FilterKind<'_>,
+    ) -> Result<U256, Self::Error> {
+        match filter {
+            ethers_providers::FilterKind::Logs(filter) => Ok(self.logs.new_filter(filter.clone())),
+            // No local block/pending-tx production to watch for yet; fall back to the inner provider.
+            other => self
+                .inner()
+                .new_filter(other)
+                .await
+                .map_err(FromErr::from),
+        }
+    }
+
+    async fn uninstall_filter<T: Into<U256> + Send + Sync>(
+        &self,
+        id: T,
+    ) -> Result<bool, Self::Error> {
+        Ok(self.logs.uninstall_filter(id.into()))
+    }
+
+    async fn get_filter_changes<T, R>(&self, id: T) -> Result<Vec<R>, Self::Error>
+    where
+        T: Into<U256> + Send + Sync,
+        R: serde::de::DeserializeOwned + Send + Sync,
+    {
+        let changes = self.logs.filter_changes(id.into());
+        let value = serde_json::to_value(changes).map_err(|e| ProviderError::from(e))?;
+        serde_json::from_value(value)
+            .map_err(|e| ProviderError::from(e))
+            .map_err(FromErr::from)
+    }
+
     // Copied from Provider::fill_transaction because we need other middleware
     // method calls to be captured by Forge
     async fn fill_transaction(
@@ -0,0 +1,496 @@
+use std::cell::RefCell;
+use std::collections::{BTreeMap, BTreeSet};
+
+use ethers_core::types::{
+    Action, AccountDiff, Address, BlockTrace, Bytes, CallAction, CallResult, CallType,
+    ChangedType, CreateAction, CreateResult, Diff, Log, Res, StateDiff, Trace, TraceType, H256,
+    U256,
+};
+use evm_gasometer::tracing::{Event as GasEvent, Listener as GasListener};
+use evm_runtime::tracing::{Event as RuntimeEvent, Listener as RuntimeListener};
+use sputnik::backend::Backend;
+use sputnik::executor::stack::tracing::{Event as HandlerEvent, Listener as HandlerListener};
+
+use crate::opcodes;
+
+// `U256::as_usize` panics if the value doesn't fit; stack words read off a
+// `Step` event are raw attacker-controlled input, so convert defensively.
+fn checked_usize(value: U256) -> Option<usize> {
+    if value > U256::from(usize::MAX) {
+        None
+    } else {
+        Some(value.as_usize())
+    }
+}
+
+/// One geth-style `debug_traceTransaction` struct-log entry.
+#[derive(Debug, Clone, Default, serde::Serialize)]
+pub struct StructLog {
+    pub pc: usize,
+    pub op: String,
+    pub gas: u64,
+    pub gas_cost: u64,
+    pub depth: u32,
+    pub stack: Vec<U256>,
+    pub memory: Vec<u8>,
+    pub storage: BTreeMap<H256, H256>,
+}
+
+/// The `debug_traceTransaction`-style struct-log result of `Forge::debug_trace_call`.
+#[derive(Debug, Clone, Default, serde::Serialize)]
+pub struct StructLogTrace {
+    pub gas: u64,
+    pub failed: bool,
+    pub return_value: Bytes,
+    pub struct_logs: Vec<StructLog>,
+}
+
+/// A single call-tracer frame (`CALL`/`CREATE`/...), nested the same way the calls were.
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct CallFrame {
+    #[serde(rename = "type")]
+    pub typ: &'static str,
+    pub from: Address,
+    pub to: Option<Address>,
+    pub value: U256,
+    pub gas: u64,
+    pub gas_used: u64,
+    pub input: Bytes,
+    pub output: Option<Bytes>,
+    pub error: Option<String>,
+    pub calls: Vec<CallFrame>,
+}
+
+/// Builds up both a flat struct-log (for `debug_traceTransaction`) and a
+/// call-frame tree (for the `callTracer`/`VmTrace` style) as Sputnik executes
+/// a single `call_raw`/`deploy`. Construct one via [`trace_call`], which
+/// wires it up to all three of Sputnik's tracing hooks (runtime steps,
+/// handler call/create/exit, gasometer cost records) at once - don't call
+/// [`StepTracer::new`] and install it yourself, since a tracer that only
+/// sees runtime steps never sees a `CALL`/`CREATE` open or close and stays
+/// flat forever.
+#[derive(Default)]
+pub struct StepTracer {
+    struct_logs: Vec<StructLog>,
+    gas_limit: u64,
+    gas_used: u64,
+    last_cost: u64,
+    frame_stack: Vec<CallFrame>,
+    root: Option<CallFrame>,
+    logs: Vec<Log>,
+    touched_accounts: BTreeSet<Address>,
+    touched_storage: BTreeSet<(Address, H256)>,
+}
+
+impl StepTracer {
+    fn new(gas_limit: u64) -> Self {
+        Self {
+            gas_limit,
+            ..Default::default()
+        }
+    }
+
+    fn begin_call(&mut self, typ: &'static str, from: Address, to: Option<Address>, value: U256, gas: u64, input: Bytes) {
+        self.touched_accounts.insert(from);
+        if let Some(to) = to {
+            self.touched_accounts.insert(to);
+        }
+        self.frame_stack.push(CallFrame {
+            typ,
+            from,
+            to,
+            value,
+            gas,
+            // Start marker: `end_call` turns this into the gas used by the frame.
+            gas_used: self.gas_used,
+            input,
+            output: None,
+            error: None,
+            calls: Vec::new(),
+        });
+    }
+
+    fn end_call(&mut self, output: Option<Bytes>, error: Option<String>) {
+        if let Some(mut frame) = self.frame_stack.pop() {
+            frame.gas_used = self.gas_used.saturating_sub(frame.gas_used);
+            frame.output = output;
+            frame.error = error;
+            match self.frame_stack.last_mut() {
+                Some(parent) => parent.calls.push(frame),
+                None => self.root = Some(frame),
+            }
+        }
+    }
+
+    pub fn into_struct_log_trace(self, failed: bool, return_value: Bytes) -> StructLogTrace {
+        StructLogTrace {
+            gas: self.struct_logs.last().map(|l| l.gas).unwrap_or_default(),
+            failed,
+            return_value,
+            struct_logs: self.struct_logs,
+        }
+    }
+
+    pub fn into_call_frame(self) -> Option<CallFrame> {
+        self.root
+    }
+
+    /// Every account touched (as a call/create participant or via
+    /// `SLOAD`/`SSTORE`) and every `(address, slot)` pair read or written,
+    /// for [`state_diff`] to diff against pre/post backend snapshots.
+    pub fn touched(&self) -> (&BTreeSet<Address>, &BTreeSet<(Address, H256)>) {
+        (&self.touched_accounts, &self.touched_storage)
+    }
+
+    /// Logs emitted so far, read directly off `LOGn`'s stack/memory args as
+    /// they executed - not from `Evm::call_raw`'s lossy `Vec<String>`.
+    pub fn logs(&self) -> &[Log] {
+        &self.logs
+    }
+
+    fn on_runtime_event(&mut self, event: RuntimeEvent) {
+        match event {
+            RuntimeEvent::Step {
+                opcode,
+                context,
+                position,
+                stack,
+                memory,
+                ..
+            } => {
+                let pc = position.as_ref().map(|p| *p).unwrap_or_default();
+                let op = opcodes::name(opcode.0);
+                self.struct_logs.push(StructLog {
+                    pc,
+                    op: op.to_string(),
+                    gas: self.gas_limit.saturating_sub(self.gas_used),
+                    gas_cost: self.last_cost,
+                    depth: self.frame_stack.len() as u32,
+                    stack: stack.data().iter().map(|w| U256::from_big_endian(&w.0)).collect(),
+                    memory: memory.data().to_vec(),
+                    storage: BTreeMap::new(),
+                });
+
+                let n_topics = match op {
+                    "LOG0" => 0,
+                    "LOG1" => 1,
+                    "LOG2" => 2,
+                    "LOG3" => 3,
+                    "LOG4" => 4,
+                    _ => return,
+                };
+                let words = stack.data();
+                let len = words.len();
+                if len < 2 + n_topics {
+                    return;
+                }
+                // offset/size are attacker-controlled stack words; a contract
+                // can push a value larger than `usize::MAX` (which would just
+                // revert the real opcode) so bail rather than panicking in
+                // `as_usize()`.
+                let (Some(offset), Some(size)) = (
+                    checked_usize(U256::from_big_endian(&words[len - 1].0)),
+                    checked_usize(U256::from_big_endian(&words[len - 2].0)),
+                ) else {
+                    return;
+                };
+                let topics: Vec<H256> = (0..n_topics).map(|i| H256(words[len - 3 - i].0)).collect();
+                let mem = memory.data();
+                if offset.saturating_add(size) > mem.len() {
+                    return;
+                }
+                self.logs.push(Log {
+                    address: context.address,
+                    topics,
+                    data: mem[offset..offset + size].to_vec().into(),
+                    ..Default::default()
+                });
+            }
+            RuntimeEvent::StepResult { .. } => {}
+            RuntimeEvent::SLoad { address, index, value } => {
+                if let Some(last) = self.struct_logs.last_mut() {
+                    last.storage.insert(index, value);
+                }
+                self.touched_accounts.insert(address);
+                self.touched_storage.insert((address, index));
+            }
+            RuntimeEvent::SStore { address, index, value } => {
+                if let Some(last) = self.struct_logs.last_mut() {
+                    last.storage.insert(index, value);
+                }
+                self.touched_accounts.insert(address);
+                self.touched_storage.insert((address, index));
+            }
+        }
+    }
+
+    // The gasometer fires an opcode's cost record from `pre_validate`, i.e.
+    // after the `Step` event for that opcode has already pushed its
+    // struct-log entry, so the cost is written back into that last entry.
+    fn on_gas_event(&mut self, event: GasEvent) {
+        let cost = match event {
+            GasEvent::RecordCost { cost, .. } => Some(cost),
+            GasEvent::RecordDynamicCost { gas_cost, .. } => Some(gas_cost),
+            GasEvent::RecordTransaction { cost } => Some(cost),
+            GasEvent::RecordRefund { .. } | GasEvent::RecordStipend { .. } => None,
+        };
+        if let Some(cost) = cost {
+            self.last_cost = cost;
+            if let Some(last) = self.struct_logs.last_mut() {
+                last.gas_cost = cost;
+            }
+            self.gas_used = self.gas_used.saturating_add(cost);
+        }
+    }
+
+    fn on_handler_event(&mut self, event: HandlerEvent) {
+        match event {
+            HandlerEvent::TransactCall {
+                caller,
+                address,
+                value,
+                data,
+                gas_limit,
+            } => self.begin_call("CALL", caller, Some(address), value, gas_limit, data.into()),
+            HandlerEvent::TransactCreate {
+                caller,
+                address,
+                value,
+                init_code,
+                gas_limit,
+            } => self.begin_call("CREATE", caller, Some(address), value, gas_limit, init_code.into()),
+            HandlerEvent::TransactCreate2 {
+                caller,
+                address,
+                value,
+                init_code,
+                gas_limit,
+                ..
+            } => self.begin_call("CREATE2", caller, Some(address), value, gas_limit, init_code.into()),
+            HandlerEvent::Call {
+                code_address,
+                transfer,
+                input,
+                target_gas,
+                context,
+                ..
+            } => self.begin_call(
+                "CALL",
+                context.caller,
+                Some(code_address),
+                transfer.as_ref().map(|t| t.value).unwrap_or_default(),
+                target_gas.unwrap_or_default(),
+                input.to_vec().into(),
+            ),
+            HandlerEvent::Create {
+                caller,
+                address,
+                value,
+                init_code,
+                target_gas,
+                ..
+            } => self.begin_call("CREATE", caller, Some(address), value, target_gas.unwrap_or_default(), init_code.to_vec().into()),
+            HandlerEvent::Exit { reason, return_value } => {
+                let error = (!reason.is_succeed()).then(|| format!("{:?}", reason));
+                self.end_call(Some(return_value.to_vec().into()), error);
+            }
+            HandlerEvent::Suicide { .. } | HandlerEvent::PrecompileSubcall { .. } => {}
+        }
+    }
+}
+
+struct RuntimeSide<'a>(&'a RefCell<StepTracer>);
+impl<'a> RuntimeListener for RuntimeSide<'a> {
+    fn event(&mut self, event: RuntimeEvent) {
+        self.0.borrow_mut().on_runtime_event(event);
+    }
+}
+
+struct GasSide<'a>(&'a RefCell<StepTracer>);
+impl<'a> GasListener for GasSide<'a> {
+    fn event(&mut self, event: GasEvent) {
+        self.0.borrow_mut().on_gas_event(event);
+    }
+}
+
+struct HandlerSide<'a>(&'a RefCell<StepTracer>);
+impl<'a> HandlerListener for HandlerSide<'a> {
+    fn event(&mut self, event: HandlerEvent) {
+        self.0.borrow_mut().on_handler_event(event);
+    }
+}
+
+/// Runs `f` (a `call_raw`/`deploy`) with a [`StepTracer`] installed across
+/// all three of Sputnik's tracing hooks at once, and hands back both `f`'s
+/// result and the finished tracer.
+//
+// Each hook's `using` takes `&mut dyn Listener` and the three Listener
+// traits live in three different modules, so there's no single type that
+// can implement all of them and be threaded through one `using` call.
+// Installing three separate thin wrapper listeners around a shared
+// `RefCell<StepTracer>` sidesteps that: the borrow checker only ever sees
+// the wrappers borrowed mutably (never `StepTracer` itself twice), and the
+// `RefCell` borrows they take are always released before the next event
+// fires, since events arrive one at a time as Sputnik steps the EVM
+// synchronously on this thread.
+pub fn trace_call<F, R>(gas_limit: u64, f: F) -> (R, StepTracer)
+where
+    F: FnOnce() -> R,
+{
+    let cell = RefCell::new(StepTracer::new(gas_limit));
+    let mut runtime_side = RuntimeSide(&cell);
+    let mut gas_side = GasSide(&cell);
+    let mut handler_side = HandlerSide(&cell);
+    let out = evm_runtime::tracing::using(&mut runtime_side, || {
+        evm_gasometer::tracing::using(&mut gas_side, || {
+            sputnik::executor::stack::tracing::using(&mut handler_side, f)
+        })
+    });
+    (out, cell.into_inner())
+}
+
+// Recurse a `CallFrame` tree into Parity-style `Trace` entries, the shape
+// `Middleware::trace_call`'s `Trace`/`TraceType::Trace` response expects.
+fn frame_to_traces(frame: &CallFrame, address: &mut Vec<usize>, out: &mut Vec<Trace>) {
+    let call_type = match frame.typ {
+        "CREATE" | "CREATE2" => None,
+        "STATICCALL" => Some(CallType::StaticCall),
+        "DELEGATECALL" => Some(CallType::DelegateCall),
+        _ => Some(CallType::Call),
+    };
+
+    let action = match call_type {
+        Some(call_type) => Action::Call(CallAction {
+            from: frame.from,
+            to: frame.to.unwrap_or_default(),
+            value: frame.value,
+            gas: frame.gas.into(),
+            input: frame.input.clone(),
+            call_type,
+        }),
+        None => Action::Create(CreateAction {
+            from: frame.from,
+            value: frame.value,
+            gas: frame.gas.into(),
+            init: frame.input.clone(),
+        }),
+    };
+
+    let result = frame.error.is_none().then(|| match call_type {
+        Some(_) => Res::Call(CallResult {
+            gas_used: frame.gas_used.into(),
+            output: frame.output.clone().unwrap_or_default(),
+        }),
+        None => Res::Create(CreateResult {
+            gas_used: frame.gas_used.into(),
+            code: frame.output.clone().unwrap_or_default(),
+            address: frame.to.unwrap_or_default(),
+        }),
+    });
+
+    out.push(Trace {
+        action,
+        result,
+        trace_address: address.clone(),
+        subtraces: frame.calls.len(),
+        transaction_position: None,
+        transaction_hash: None,
+        block_number: 0,
+        block_hash: Default::default(),
+        action_type: Default::default(),
+        error: frame.error.clone(),
+    });
+
+    for (i, child) in frame.calls.iter().enumerate() {
+        address.push(i);
+        frame_to_traces(child, address, out);
+        address.pop();
+    }
+}
+
+/// A `TraceType` was requested that this tracer can't fill in yet.
+#[derive(Debug, thiserror::Error)]
+#[error("trace type {0:?} is not supported yet")]
+pub struct UnsupportedTraceType(pub TraceType);
+
+fn diff_of<T: PartialEq>(pre: T, post: T) -> Diff<T> {
+    if pre == post {
+        Diff::Same
+    } else {
+        Diff::Changed(ChangedType { from: pre, to: post })
+    }
+}
+
+/// Diffs `pre`/`post` backend snapshots (the same clone/reset-state pair
+/// `Middleware::trace_call` already takes around a call) into Parity's
+/// `StateDiff` shape, covering every account/slot in `touched` - i.e.
+/// everything [`StepTracer::touched`] saw as a call/create participant or an
+/// `SLOAD`/`SSTORE`.
+//
+// Doesn't distinguish `Diff::Born`/`Diff::Died` from `Diff::Changed`: that
+// needs "did this account exist before" info this crate doesn't track
+// per-account yet, so every real change is reported as `Changed`.
+pub fn state_diff<S: Backend>(
+    pre: &S,
+    post: &S,
+    touched: (&BTreeSet<Address>, &BTreeSet<(Address, H256)>),
+) -> StateDiff {
+    let (touched_accounts, touched_storage) = touched;
+    let mut accounts = BTreeMap::new();
+    for &address in touched_accounts {
+        let pre_basic = pre.basic(address);
+        let post_basic = post.basic(address);
+        let pre_code = pre.code(address);
+        let post_code = post.code(address);
+
+        let mut storage = BTreeMap::new();
+        for &(slot_address, slot) in touched_storage {
+            if slot_address != address {
+                continue;
+            }
+            storage.insert(slot, diff_of(pre.storage(address, slot), post.storage(address, slot)));
+        }
+
+        accounts.insert(
+            address,
+            AccountDiff {
+                balance: diff_of(pre_basic.balance, post_basic.balance),
+                nonce: diff_of(pre_basic.nonce, post_basic.nonce),
+                code: diff_of(Bytes::from(pre_code), Bytes::from(post_code)),
+                storage,
+            },
+        );
+    }
+    StateDiff(accounts)
+}
+
+/// Builds the `BlockTrace` response for `Middleware::trace_call`, filling in
+/// only the sections the caller actually asked for via `types`. `state_diff`
+/// is `Some` only when the caller passes one in (see [`state_diff`]);
+/// `vm_trace` isn't supported yet, so requesting `TraceType::VmTrace` errors
+/// instead of silently coming back empty.
+pub fn to_block_trace(
+    frame: Option<&CallFrame>,
+    types: &[TraceType],
+    diff: Option<StateDiff>,
+) -> Result<BlockTrace, UnsupportedTraceType> {
+    if types.contains(&TraceType::VmTrace) {
+        return Err(UnsupportedTraceType(TraceType::VmTrace));
+    }
+
+    let trace = types.contains(&TraceType::Trace).then(|| {
+        let mut out = Vec::new();
+        if let Some(frame) = frame {
+            frame_to_traces(frame, &mut Vec::new(), &mut out);
+        }
+        out
+    });
+
+    Ok(BlockTrace {
+        output: frame.and_then(|f| f.output.clone()).unwrap_or_default(),
+        state_diff: types.contains(&TraceType::StateDiff).then(|| diff).flatten(),
+        trace,
+        vm_trace: None,
+        transaction_hash: None,
+    })
+}